@@ -0,0 +1,258 @@
+//! Basic and Digest (RFC 7616) authentication.
+
+use crate::model::{HeaderValue, Request};
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+
+/// Tracks the Digest `nc` (nonce count) [RFC 7616](https://httpwg.org/specs/rfc7616.html)
+/// requires per distinct server nonce, so a new challenge always starts back at `00000001`
+/// instead of drifting upward from one counter shared across unrelated nonces.
+#[derive(Default)]
+pub(crate) struct DigestNonceCounter {
+    counts: std::sync::Mutex<std::collections::HashMap<String, u64>>,
+}
+
+impl DigestNonceCounter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the next `nc` value for `nonce`, starting at 1.
+    pub(crate) fn next(&self, nonce: &str) -> u64 {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(nonce.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    }
+}
+
+/// Credentials a [`Client`](crate::Client) should authenticate its requests with.
+#[derive(Clone)]
+pub enum Auth {
+    /// [RFC 7617](https://httpwg.org/specs/rfc7617.html) Basic authentication.
+    Basic { user: String, password: String },
+    /// [RFC 7616](https://httpwg.org/specs/rfc7616.html) Digest authentication.
+    Digest { user: String, password: String },
+}
+
+/// Sets `Authorization: Basic <base64(user:password)>` on `request`, unless it already has an
+/// `Authorization` header.
+pub(crate) fn apply_basic(request: &mut Request, user: &str, password: &str) {
+    use crate::model::HeaderName;
+    if request.headers().contains(&HeaderName::AUTHORIZATION) {
+        return;
+    }
+    let credentials = base64_encode(format!("{}:{}", user, password).as_bytes());
+    request.headers_mut().set(
+        HeaderName::AUTHORIZATION,
+        HeaderValue::new_unchecked(format!("Basic {}", credentials)),
+    );
+}
+
+/// A parsed `WWW-Authenticate: Digest ...` challenge.
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    qop: Option<String>,
+    opaque: Option<String>,
+    algorithm: String,
+}
+
+impl DigestChallenge {
+    fn parse(header: &str) -> Option<Self> {
+        let rest = header.trim().strip_prefix("Digest")?.trim();
+        let mut realm = None;
+        let mut nonce = None;
+        let mut qop = None;
+        let mut opaque = None;
+        let mut algorithm = "MD5".to_string();
+        for param in rest.split(',') {
+            let (key, value) = param.split_once('=')?;
+            let key = key.trim();
+            let value = value.trim().trim_matches('"');
+            match key {
+                "realm" => realm = Some(value.to_string()),
+                "nonce" => nonce = Some(value.to_string()),
+                "qop" => qop = Some(value.to_string()),
+                "opaque" => opaque = Some(value.to_string()),
+                "algorithm" => algorithm = value.to_string(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            realm: realm?,
+            nonce: nonce?,
+            qop,
+            opaque,
+            algorithm,
+        })
+    }
+}
+
+/// Extracts the server `nonce` out of a `WWW-Authenticate: Digest ...` header, so the caller can
+/// look up the right `nc` for it (via [`DigestNonceCounter`]) before calling
+/// [`digest_authorization`].
+pub(crate) fn digest_nonce(challenge_header: &str) -> Option<String> {
+    Some(DigestChallenge::parse(challenge_header)?.nonce)
+}
+
+/// Builds an `Authorization: Digest ...` header answering `challenge_header` for a request to
+/// `uri` (path + query) with `method`, using `nc` as the request counter for this nonce (see
+/// [`DigestNonceCounter`]).
+pub(crate) fn digest_authorization(
+    challenge_header: &str,
+    method: &str,
+    uri: &str,
+    user: &str,
+    password: &str,
+    nc: u64,
+) -> Option<HeaderValue> {
+    let challenge = DigestChallenge::parse(challenge_header)?;
+    let ha1 = hash(
+        &challenge.algorithm,
+        &format!("{}:{}:{}", user, challenge.realm, password),
+    );
+    let ha2 = hash(&challenge.algorithm, &format!("{}:{}", method, uri));
+    let qop = challenge
+        .qop
+        .as_deref()
+        .and_then(|qop| qop.split(',').map(str::trim).find(|q| *q == "auth"));
+    let cnonce = format!("{:016x}", random_u64());
+    let nc = format!("{:08x}", nc);
+
+    let response = match qop {
+        Some(qop) => hash(
+            &challenge.algorithm,
+            &format!(
+                "{}:{}:{}:{}:{}:{}",
+                ha1, challenge.nonce, nc, cnonce, qop, ha2
+            ),
+        ),
+        None => hash(
+            &challenge.algorithm,
+            &format!("{}:{}:{}", ha1, challenge.nonce, ha2),
+        ),
+    };
+
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", response=\"{}\", algorithm={}",
+        user, challenge.realm, challenge.nonce, uri, response, challenge.algorithm
+    );
+    if let Some(qop) = qop {
+        write!(header, ", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce).ok()?;
+    }
+    if let Some(opaque) = &challenge.opaque {
+        write!(header, ", opaque=\"{}\"", opaque).ok()?;
+    }
+    HeaderValue::try_from(header).ok()
+}
+
+fn hash(algorithm: &str, data: &str) -> String {
+    if algorithm.eq_ignore_ascii_case("SHA-256") {
+        use sha2::{Digest, Sha256};
+        hex(&Sha256::digest(data.as_bytes()))
+    } else {
+        hex(&md5::compute(data.as_bytes()).0)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(out, "{:02x}", byte).unwrap();
+    }
+    out
+}
+
+fn random_u64() -> u64 {
+    use rand::Rng;
+    rand::thread_rng().gen()
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    base64::encode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{HeaderName, Method};
+
+    #[test]
+    fn digest_nonce_counter_restarts_per_nonce() {
+        let counter = DigestNonceCounter::new();
+        assert_eq!(counter.next("nonce-a"), 1);
+        assert_eq!(counter.next("nonce-a"), 2);
+        assert_eq!(counter.next("nonce-b"), 1);
+        assert_eq!(counter.next("nonce-a"), 3);
+    }
+
+    fn authorization_header(request: &Request) -> Option<String> {
+        request
+            .headers()
+            .find(|(name, _)| **name == HeaderName::AUTHORIZATION)
+            .and_then(|(_, value)| value.to_str().ok().map(str::to_string))
+    }
+
+    #[test]
+    fn apply_basic_sets_the_authorization_header() {
+        let mut request =
+            Request::builder(Method::GET, "http://example.com".parse().unwrap()).build();
+        apply_basic(&mut request, "Aladdin", "open sesame");
+        assert_eq!(
+            authorization_header(&request).as_deref(),
+            Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+        );
+    }
+
+    #[test]
+    fn apply_basic_does_not_override_an_existing_header() {
+        let mut request =
+            Request::builder(Method::GET, "http://example.com".parse().unwrap()).build();
+        request.headers_mut().set(
+            HeaderName::AUTHORIZATION,
+            HeaderValue::new_unchecked("Bearer token"),
+        );
+        apply_basic(&mut request, "Aladdin", "open sesame");
+        assert_eq!(authorization_header(&request).as_deref(), Some("Bearer token"));
+    }
+
+    #[test]
+    fn digest_nonce_extracts_the_server_nonce() {
+        let challenge = r#"Digest realm="test", nonce="abc123", qop="auth""#;
+        assert_eq!(digest_nonce(challenge).as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn digest_nonce_returns_none_for_malformed_challenge() {
+        assert_eq!(digest_nonce("not a digest challenge"), None);
+    }
+
+    #[test]
+    fn digest_authorization_includes_qop_and_nc_when_offered() {
+        let challenge = r#"Digest realm="testrealm@host.com", qop="auth", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#;
+        let header = digest_authorization(
+            challenge,
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            1,
+        )
+        .unwrap();
+        let header = header.to_str().unwrap();
+        assert!(header.starts_with("Digest username=\"Mufasa\""));
+        assert!(header.contains("nc=00000001"));
+        assert!(header.contains("qop=auth"));
+        assert!(header.contains("opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""));
+    }
+
+    #[test]
+    fn digest_authorization_omits_qop_when_not_offered() {
+        let challenge = r#"Digest realm="testrealm@host.com", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093""#;
+        let header =
+            digest_authorization(challenge, "GET", "/dir/index.html", "Mufasa", "Circle Of Life", 1)
+                .unwrap();
+        assert!(!header.to_str().unwrap().contains("qop="));
+    }
+}
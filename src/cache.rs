@@ -0,0 +1,256 @@
+//! In-memory HTTP response cache with conditional revalidation, modeled on the `Cache-Control`
+//! handling of typical browser/runtime HTTP caches.
+
+use crate::model::{HeaderName, HeaderValue, Response, Status};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    must_revalidate: bool,
+    max_age: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(value: &str) -> Self {
+        let mut cache_control = Self::default();
+        for directive in value.split(',') {
+            let directive = directive.trim();
+            if directive.eq_ignore_ascii_case("no-store") {
+                cache_control.no_store = true;
+            } else if directive.eq_ignore_ascii_case("no-cache") {
+                cache_control.no_cache = true;
+            } else if directive.eq_ignore_ascii_case("must-revalidate") {
+                cache_control.must_revalidate = true;
+            } else if let Some(seconds) = directive
+                .split_once('=')
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("max-age"))
+                .map(|(_, value)| value.trim())
+            {
+                cache_control.max_age = seconds.parse().ok().map(Duration::from_secs);
+            }
+        }
+        cache_control
+    }
+
+    /// Whether a response carrying this `Cache-Control` is worth storing at all. `max-age` is
+    /// the common case, but a response with no freshness lifetime of its own is still worth
+    /// keeping around when it has a validator (`ETag`/`Last-Modified`) purely for conditional
+    /// revalidation (`has_validator`) — `no-store` is the only thing that unconditionally rules
+    /// storage out.
+    fn is_cacheable(&self, has_validator: bool) -> bool {
+        !self.no_store && (self.max_age.is_some() || has_validator)
+    }
+}
+
+/// A previously-received response, stored verbatim so it can be replayed or revalidated.
+struct Entry {
+    status: Status,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Vec<u8>,
+    stored_at: Instant,
+    cache_control: CacheControl,
+}
+
+impl Entry {
+    fn header(&self, name: &HeaderName) -> Option<&HeaderValue> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    fn is_fresh(&self) -> bool {
+        if self.cache_control.no_cache {
+            return false;
+        }
+        match self.cache_control.max_age {
+            Some(max_age) => self.stored_at.elapsed() < max_age,
+            None => false,
+        }
+    }
+
+    fn to_response(&self) -> Response {
+        let mut builder = Response::builder(self.status);
+        for (name, value) in &self.headers {
+            builder.headers_mut().set(name.clone(), value.clone());
+        }
+        builder.with_body(Cursor::new(self.body.clone())).build()
+    }
+}
+
+/// What the cache knows about a URL before a request is sent.
+pub(crate) enum Lookup {
+    /// Serve this response directly, no network round trip needed.
+    Fresh(Response),
+    /// The stored entry is stale; attach these conditional headers and send the request anyway.
+    Revalidate {
+        etag: Option<HeaderValue>,
+        last_modified: Option<HeaderValue>,
+    },
+}
+
+/// An in-memory cache of final (post-redirect) GET/HEAD responses, keyed by URL.
+pub(crate) struct ResponseCache {
+    capacity: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub(crate) fn lookup(&self, url: &str) -> Option<Lookup> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(url)?;
+        if entry.is_fresh() {
+            return Some(Lookup::Fresh(entry.to_response()));
+        }
+        let etag = entry.header(&HeaderName::ETAG).cloned();
+        let last_modified = entry.header(&HeaderName::LAST_MODIFIED).cloned();
+        if etag.is_none() && last_modified.is_none() {
+            // Nothing to revalidate against: a stale entry that must be revalidated can never
+            // be served again, so there is no point keeping it around.
+            if entry.cache_control.must_revalidate {
+                entries.remove(url);
+            }
+            return None;
+        }
+        Some(Lookup::Revalidate {
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Refreshes the stored headers/timestamp of `url` after a `304 Not Modified`, and returns
+    /// the (still cached) body to serve for this request. Returns `None` if nothing was cached.
+    pub(crate) fn refresh(&self, url: &str, response: &Response) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(url)?;
+        for (name, value) in response.headers() {
+            entry.headers.retain(|(n, _)| n != name);
+            entry.headers.push((name.clone(), value.clone()));
+        }
+        if let Some(value) = response.header(&HeaderName::CACHE_CONTROL) {
+            entry.cache_control = CacheControl::parse(value.to_str().ok()?);
+        }
+        entry.stored_at = Instant::now();
+        Some(entry.to_response())
+    }
+
+    /// Consumes `response`'s body, stores the response for `url` if it is cacheable (evicting
+    /// an arbitrary entry first if the cache is already at capacity), and returns a fresh
+    /// `Response` equivalent to the one consumed so the caller can still read it.
+    pub(crate) fn store_and_serve(&self, url: String, response: Response) -> Response {
+        let status = response.status();
+        let headers: Vec<_> = response
+            .headers()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let mut body = Vec::new();
+        let _ = response.into_body().read_to_end(&mut body);
+
+        let cache_control = headers
+            .iter()
+            .find(|(name, _)| *name == HeaderName::CACHE_CONTROL)
+            .and_then(|(_, value)| value.to_str().ok())
+            .map(CacheControl::parse)
+            .unwrap_or_default();
+        let has_validator = headers
+            .iter()
+            .any(|(name, _)| *name == HeaderName::ETAG || *name == HeaderName::LAST_MODIFIED);
+        if self.capacity > 0 && cache_control.is_cacheable(has_validator) {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() >= self.capacity && !entries.contains_key(&url) {
+                if let Some(key) = entries.keys().next().cloned() {
+                    entries.remove(&key);
+                }
+            }
+            entries.insert(
+                url,
+                Entry {
+                    status,
+                    headers: headers.clone(),
+                    body: body.clone(),
+                    stored_at: Instant::now(),
+                    cache_control,
+                },
+            );
+        }
+
+        let mut builder = Response::builder(status);
+        for (name, value) in headers {
+            builder.headers_mut().set(name, value);
+        }
+        builder.with_body(Cursor::new(body)).build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Response;
+
+    #[test]
+    fn cache_control_no_store_blocks_storage_even_with_validator() {
+        let cache_control = CacheControl::parse("no-store, max-age=60");
+        assert!(!cache_control.is_cacheable(true));
+        assert!(!cache_control.is_cacheable(false));
+    }
+
+    #[test]
+    fn cache_control_max_age_alone_is_cacheable() {
+        let cache_control = CacheControl::parse("max-age=60");
+        assert!(cache_control.is_cacheable(false));
+    }
+
+    #[test]
+    fn cache_control_without_max_age_is_still_cacheable_with_a_validator() {
+        // e.g. `Cache-Control: no-cache` (or no header at all) plus an `ETag`: nothing is fresh
+        // enough to serve without asking the server, but it's worth keeping around so the next
+        // request can revalidate with `If-None-Match` instead of re-downloading the body.
+        let cache_control = CacheControl::parse("no-cache");
+        assert!(cache_control.is_cacheable(true));
+        assert!(!cache_control.is_cacheable(false));
+    }
+
+    #[test]
+    fn store_and_serve_keeps_etag_only_responses_for_revalidation() {
+        let cache = ResponseCache::new(10);
+        let mut builder = Response::builder(Status::OK);
+        builder
+            .headers_mut()
+            .set(HeaderName::ETAG, HeaderValue::new_unchecked("\"v1\""));
+        let response = builder.with_body(Cursor::new(b"hello".to_vec())).build();
+        cache.store_and_serve("http://example.com/a".to_string(), response);
+
+        match cache.lookup("http://example.com/a") {
+            Some(Lookup::Revalidate { etag, .. }) => {
+                assert_eq!(etag.unwrap().as_ref(), b"\"v1\"");
+            }
+            other => panic!("expected a Revalidate lookup, got {:?}", other.is_some()),
+        }
+    }
+
+    #[test]
+    fn zero_capacity_cache_never_stores_anything() {
+        let cache = ResponseCache::new(0);
+        let mut builder = Response::builder(Status::OK);
+        builder
+            .headers_mut()
+            .set(HeaderName::CACHE_CONTROL, HeaderValue::new_unchecked("max-age=60"));
+        let response = builder.with_body(Cursor::new(b"hello".to_vec())).build();
+        cache.store_and_serve("http://example.com/a".to_string(), response);
+
+        assert!(cache.lookup("http://example.com/a").is_none());
+    }
+}
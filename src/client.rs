@@ -1,9 +1,14 @@
 //! Simple HTTP client
 
+use crate::cache::{Lookup, ResponseCache};
+use crate::auth::{self, Auth};
+use crate::decompress::{is_supported_encoding, DecodedBody};
+use crate::hsts::HstsStore;
 use crate::io::{decode_response, encode_request};
 use crate::model::{
     HeaderName, HeaderValue, InvalidHeader, Method, Request, Response, Status, Url,
 };
+use crate::pool::{ConnectionPool, PooledStream, TrackedStream};
 use crate::utils::{invalid_data_error, invalid_input_error};
 // #[cfg(any(feature = "native-tls", feature = "rustls"))]
 // use lazy_static::lazy_static;
@@ -14,12 +19,15 @@ use crate::utils::{invalid_data_error, invalid_input_error};
 // #[cfg(feature = "rustls")]
 // use rustls_native_certs::load_native_certs;
 use std::convert::TryFrom;
-use std::io::{BufReader, BufWriter, Error, ErrorKind, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
 use std::net::SocketAddr;
+use std::path::Path;
 // use std::net::{SocketAddr, TcpStream};
 // #[cfg(any(feature = "native-tls", feature = "rustls"))]
 // use std::sync::Arc;
 use std::time::Duration;
+use std::sync::Arc;
 use lunatic::net::{TcpStream, SocketAddrIterator, TlsStream};
 // use std::net::SocketAddr;
 
@@ -62,8 +70,6 @@ use lunatic::net::{TcpStream, SocketAddrIterator, TlsStream};
 ///
 /// The client does not follow redirections by default. Use [`Client::set_redirection_limit`] to set a limit to the number of consecutive redirections the server should follow.
 ///
-/// Missing: HSTS support, authentication and keep alive.
-///
 /// ```
 /// use oxhttp::Client;
 /// use oxhttp::model::{Request, Method, Status, HeaderName};
@@ -76,11 +82,41 @@ use lunatic::net::{TcpStream, SocketAddrIterator, TlsStream};
 /// let body = response.into_body().to_string()?;
 /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
 /// ```
-#[derive(Default)]
 pub struct Client {
     timeout: Option<Duration>,
     user_agent: Option<HeaderValue>,
     redirection_limit: usize,
+    keep_alive: bool,
+    pool: Arc<ConnectionPool>,
+    auto_decompress: bool,
+    cache: Option<ResponseCache>,
+    hsts_enabled: bool,
+    hsts: HstsStore,
+    auth: Option<Auth>,
+    digest_nc: auth::DigestNonceCounter,
+    connect_retries: u32,
+    connect_retry_base_delay: Duration,
+}
+
+impl Default for Client {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            user_agent: None,
+            redirection_limit: 0,
+            keep_alive: false,
+            pool: Arc::new(ConnectionPool::new(DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST)),
+            auto_decompress: false,
+            cache: None,
+            hsts_enabled: false,
+            hsts: HstsStore::new(),
+            auth: None,
+            digest_nc: auth::DigestNonceCounter::new(),
+            connect_retries: 0,
+            connect_retry_base_delay: Duration::from_millis(200),
+        }
+    }
 }
 
 impl Client {
@@ -112,11 +148,183 @@ impl Client {
         self.redirection_limit = limit;
     }
 
+    /// Enables keeping idle connections open and reusing them for later requests to the same
+    /// origin, instead of always sending `Connection: close`. Disabled by default.
+    #[inline]
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive = keep_alive;
+    }
+
+    /// Sets the maximum number of idle connections kept open per origin when keep-alive is
+    /// enabled. Defaults to [`DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST`].
+    #[inline]
+    pub fn set_max_idle_connections_per_host(&mut self, max: usize) {
+        self.pool = Arc::new(ConnectionPool::new(max));
+    }
+
+    /// Enables transparent `gzip`/`deflate` response decoding. When enabled, `single_request`
+    /// advertises `Accept-Encoding: gzip, deflate` and decodes a supported `Content-Encoding` on
+    /// the fly, unless the caller already set their own `Accept-Encoding` header, in which case
+    /// the request and its response body are left untouched. Disabled by default.
+    #[inline]
+    pub fn set_auto_decompress(&mut self, auto_decompress: bool) {
+        self.auto_decompress = auto_decompress;
+    }
+
+    /// Enables an in-memory cache of up to `capacity` final (post-redirect) GET/HEAD responses,
+    /// revalidated with `If-None-Match`/`If-Modified-Since` once they go stale. Disabled by
+    /// default; calling this again replaces the cache with an empty one of the new capacity.
+    #[inline]
+    pub fn set_cache(&mut self, capacity: usize) {
+        self.cache = Some(ResponseCache::new(capacity));
+    }
+
+    /// Clears the response cache, if one is enabled.
+    #[inline]
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// Enables HSTS: `Strict-Transport-Security` response headers are recorded, and subsequent
+    /// plain HTTP requests to a host (or a subdomain of one, when `includeSubDomains` was set)
+    /// with a non-expired entry are transparently upgraded to HTTPS. Disabled by default.
+    #[inline]
+    pub fn enable_hsts(&mut self, enabled: bool) {
+        self.hsts_enabled = enabled;
+    }
+
+    /// Seeds an HSTS entry for `host` as if it had already sent a `Strict-Transport-Security`
+    /// header, e.g. to bootstrap a small preload list of known-HTTPS-only hosts.
+    #[inline]
+    pub fn preload_hsts(&self, host: impl Into<String>, include_subdomains: bool) {
+        self.hsts.preload(host, include_subdomains);
+    }
+
+    /// Authenticates requests with `auth`. `Auth::Basic` credentials are sent preemptively;
+    /// `Auth::Digest` credentials are only sent after the server challenges a request with a
+    /// `401` carrying a `WWW-Authenticate: Digest` header.
+    #[inline]
+    pub fn set_auth(&mut self, auth: Auth) {
+        self.auth = Some(auth);
+    }
+
+    /// Retries a failed connection attempt (every resolved address refused or timed out) up to
+    /// `max` additional times, re-resolving the host and sleeping `base_delay * 2^attempt`
+    /// (capped, with a little jitter) between rounds. Defaults to no retries.
+    #[inline]
+    pub fn set_connect_retries(&mut self, max: u32, base_delay: Duration) {
+        self.connect_retries = max;
+        self.connect_retry_base_delay = base_delay;
+    }
+
+    /// Streams `request`'s response body directly to the file at `path`, instead of
+    /// materializing it in memory. If `path` already exists with `N` bytes, sends
+    /// `Range: bytes=N-` and appends to it on a `206 Partial Content` reply; starts over from
+    /// scratch on a plain `200 OK` (the server ignored the range). Returns an error if the
+    /// transfer ends before the advertised length is reached.
+    ///
+    /// `progress`, when given, is called after every chunk written with
+    /// `(bytes_downloaded, total_bytes)`; `total_bytes` is `None` when the server didn't report
+    /// a length.
+    pub fn download(
+        &self,
+        mut request: Request,
+        path: impl AsRef<Path>,
+        mut progress: Option<&mut dyn FnMut(u64, Option<u64>)>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let resume_from = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        if resume_from > 0 {
+            request.headers_mut().set(
+                HeaderName::RANGE,
+                HeaderValue::new_unchecked(format!("bytes={}-", resume_from)),
+            );
+        }
+
+        let response = self.request(request)?;
+        let (mut file, mut downloaded) = match response.status() {
+            Status::PARTIAL_CONTENT if resume_from > 0 => {
+                (OpenOptions::new().append(true).open(path)?, resume_from)
+            }
+            Status::OK => (File::create(path)?, 0),
+            status => {
+                return Err(invalid_data_error(format!(
+                    "Unexpected status downloading {}: {}",
+                    path.display(),
+                    status
+                )))
+            }
+        };
+        let total = total_download_size(&response, downloaded);
+
+        let mut body = response.into_body();
+        let mut buffer = [0; 8192];
+        loop {
+            let n = body.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buffer[..n])?;
+            downloaded += n as u64;
+            if let Some(progress) = progress.as_deref_mut() {
+                progress(downloaded, total);
+            }
+        }
+
+        if let Some(total) = total {
+            if downloaded != total {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "Download of {} was interrupted: got {} of {} bytes",
+                        path.display(),
+                        downloaded,
+                        total
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn request(&self, mut request: Request) -> Result<Response> {
+        let cacheable_method = matches!(request.method(), &Method::GET | &Method::HEAD);
+        // The host Basic credentials were issued for, i.e. the host of the request before any
+        // redirect. Kept fixed across the loop so a cross-origin redirect target never gets the
+        // credentials re-applied after they were stripped below.
+        let auth_host = request.url().host_str().map(str::to_string);
+
         // Loops the number of allowed redirections + 1
         for _ in 0..(self.redirection_limit + 1) {
             let previous_method = request.method().clone();
-            let response = self.single_request(&mut request)?;
+            let url_key = request.url().to_string();
+
+            if cacheable_method {
+                if let Some(cache) = &self.cache {
+                    match cache.lookup(&url_key) {
+                        Some(Lookup::Fresh(response)) => return Ok(response),
+                        Some(Lookup::Revalidate {
+                            etag,
+                            last_modified,
+                        }) => {
+                            if let Some(etag) = etag {
+                                request.headers_mut().set(HeaderName::IF_NONE_MATCH, etag);
+                            }
+                            if let Some(last_modified) = last_modified {
+                                request
+                                    .headers_mut()
+                                    .set(HeaderName::IF_MODIFIED_SINCE, last_modified);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+
+            let current_host_matches_auth = request.url().host_str() == auth_host.as_deref();
+            let response = self.single_request(&mut request, current_host_matches_auth)?;
             if let Some(location) = response.header(&HeaderName::LOCATION) {
                 let new_method = match response.status() {
                     Status::MOVED_PERMANENTLY | Status::FOUND | Status::SEE_OTHER => {
@@ -131,7 +339,7 @@ impl Client {
                     {
                         previous_method
                     }
-                    _ => return Ok(response),
+                    _ => return Ok(self.finalize_cacheable_response(cacheable_method, url_key, response)),
                 };
                 let location = location.to_str().map_err(invalid_data_error)?;
                 let new_url = request.url().join(location).map_err(|e| {
@@ -140,15 +348,21 @@ impl Client {
                         e, location
                     ))
                 })?;
+                let cross_origin = new_url.host_str() != request.url().host_str();
                 let mut request_builder = Request::builder(new_method, new_url);
                 for (header_name, header_value) in request.headers() {
+                    // Never forward credentials to a different host than the one they were
+                    // issued for.
+                    if cross_origin && *header_name == HeaderName::AUTHORIZATION {
+                        continue;
+                    }
                     request_builder
                         .headers_mut()
                         .set(header_name.clone(), header_value.clone());
                 }
                 request = request_builder.build();
             } else {
-                return Ok(response);
+                return Ok(self.finalize_cacheable_response(cacheable_method, url_key, response));
             }
         }
         Err(Error::new(
@@ -161,81 +375,193 @@ impl Client {
         ))
     }
 
-    #[allow(unreachable_code, clippy::needless_return)]
-    fn single_request(&self, request: &mut Request) -> Result<Response> {
-        // panic!("{}", request.url());
+    /// Serves the final response of a redirect chain through the cache: refreshes the stored
+    /// entry on a `304`, stores a fresh cacheable response, or passes it through unchanged.
+    fn finalize_cacheable_response(
+        &self,
+        cacheable_method: bool,
+        url: String,
+        response: Response,
+    ) -> Response {
+        if !cacheable_method {
+            return response;
+        }
+        match &self.cache {
+            Some(cache) if response.status() == Status::NOT_MODIFIED => {
+                cache.refresh(&url, &response).unwrap_or(response)
+            }
+            Some(cache) => cache.store_and_serve(url, response),
+            None => response,
+        }
+    }
 
-        // Additional headers
-        set_header_fallback(request, HeaderName::USER_AGENT, &self.user_agent);
+    /// Sends `request`, transparently answering a Digest authentication challenge with one
+    /// retry if [`Client::set_auth`] was given `Auth::Digest` credentials. `is_auth_host` must
+    /// be `false` once a redirect has taken `request` to a different host than the one
+    /// originally passed to [`Client::request`], so neither Basic nor Digest credentials are
+    /// ever attached to a host they weren't issued for.
+    fn single_request(&self, request: &mut Request, is_auth_host: bool) -> Result<Response> {
+        if is_auth_host {
+            if let Some(Auth::Basic { user, password }) = &self.auth {
+                auth::apply_basic(request, user, password);
+            }
+        }
+
+        let response = self.transmit(request)?;
+        if !is_auth_host || response.status() != Status::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let (user, password) = match &self.auth {
+            Some(Auth::Digest { user, password }) => (user, password),
+            _ => return Ok(response),
+        };
+        let challenge = match response
+            .header(&HeaderName::WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(challenge) => challenge.to_string(),
+            None => return Ok(response),
+        };
+        let mut uri = request.url().path().to_string();
+        if let Some(query) = request.url().query() {
+            uri.push('?');
+            uri.push_str(query);
+        }
+        let nonce = match auth::digest_nonce(&challenge) {
+            Some(nonce) => nonce,
+            None => return Ok(response),
+        };
+        let nc = self.digest_nc.next(&nonce);
+        let authorization = match auth::digest_authorization(
+            &challenge,
+            &request.method().to_string(),
+            &uri,
+            user,
+            password,
+            nc,
+        ) {
+            Some(authorization) => authorization,
+            None => return Ok(response),
+        };
         request
             .headers_mut()
-            .set(HeaderName::CONNECTION, HeaderValue::new_unchecked("close"));
-        // #[cfg(any(feature = "native-tls", feature = "rustls"))]
-        // let host = request
-        //     .url()
-        //     .host_str()
-        //     .ok_or_else(|| invalid_input_error("No host provided"))?;
-
-        match request.url().scheme() {
-            "http" => {
-                let addresses = get_and_validate_socket_addresses(request.url(), 80)?;
-                let mut stream = self.connect_tcp(addresses)?;
-                encode_request(request, BufWriter::new(&mut stream))?;
-                decode_response(BufReader::new(stream))
+            .set(HeaderName::AUTHORIZATION, authorization);
+        self.transmit(request)
+    }
+
+    fn transmit(&self, request: &mut Request) -> Result<Response> {
+        // Additional headers
+        set_header_fallback(request, HeaderName::USER_AGENT, &self.user_agent);
+        request.headers_mut().set(
+            HeaderName::CONNECTION,
+            HeaderValue::new_unchecked(if self.keep_alive { "keep-alive" } else { "close" }),
+        );
+        let caller_set_accept_encoding = request.headers().contains(&HeaderName::ACCEPT_ENCODING);
+        if self.auto_decompress && !caller_set_accept_encoding {
+            request.headers_mut().set(
+                HeaderName::ACCEPT_ENCODING,
+                HeaderValue::new_unchecked("gzip, deflate"),
+            );
+        }
 
+        let scheme = request.url().scheme();
+        let default_port = match scheme {
+            "http" => 80,
+            "https" => 443,
+            _ => {
+                return Err(invalid_input_error(format!(
+                    "Not supported URL scheme: {}",
+                    scheme
+                )))
             }
-            "https" => {
-                let addresses = get_and_validate_socket_addresses(request.url(), 443)?;
-                let mut stream = self.connect_tls(request.url())?;
-                encode_request(request, BufWriter::new(&mut stream))?;
-                decode_response(BufReader::new(stream))
-                // #[cfg(feature = "native-tls")]
-                // {
-                //     let addresses = get_and_validate_socket_addresses(request.url(), 443)?;
-                //     let stream = self.connect(&addresses)?;
-                //     let mut stream = TLS_CONNECTOR
-                //         .connect(host, stream)
-                //         .map_err(|e| Error::new(ErrorKind::Other, e))?;
-                //     encode_request(request, BufWriter::new(&mut stream))?;
-                //     return decode_response(BufReader::new(stream));
-                // }
-                // #[cfg(feature = "rustls")]
-                // {
-                //     let addresses = get_and_validate_socket_addresses(request.url(), 443)?;
-                //     let dns_name = ServerName::try_from(host).map_err(invalid_input_error)?;
-                //     let connection = ClientConnection::new(RUSTLS_CONFIG.clone(), dns_name)
-                //         .map_err(|e| Error::new(ErrorKind::Other, e))?;
-                //     let mut stream = StreamOwned::new(connection, self.connect(&addresses)?);
-                //     encode_request(request, BufWriter::new(&mut stream))?;
-                //     return decode_response(BufReader::new(stream));
-                // }
-                // #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
-                // return Err(invalid_input_error("HTTPS is not supported by the client. You should enable the `native-tls` or `rustls` feature of the `oxhttp` crate"));
+        };
+        let host = request
+            .url()
+            .host_str()
+            .ok_or_else(|| invalid_input_error("No host provided"))?
+            .to_string();
+        let port = request.url().port().unwrap_or(default_port);
+
+        // HSTS: transparently upgrade the connection (but not the request's own URL) when the
+        // host, or a parent domain with `includeSubDomains`, asked to be reached over HTTPS
+        // only. A `Strict-Transport-Security` header is only recorded (below) when it arrives
+        // over HTTPS, which includes a connection upgraded here; over plain HTTP it is ignored.
+        let (scheme, port) = if scheme == "http" && self.hsts_enabled && self.hsts.should_upgrade(&host) {
+            ("https", if request.url().port().is_none() { 443 } else { port })
+        } else {
+            (scheme, port)
+        };
+        let key = (scheme.to_string(), host.clone(), port);
+
+        let stream = if self.keep_alive {
+            self.pool.take(&key)
+        } else {
+            None
+        };
+        let stream = match stream {
+            Some(stream) => stream,
+            None => match scheme {
+                "http" => PooledStream::Tcp(self.connect_tcp(&host, port)?),
+                "https" => PooledStream::Tls(self.connect_tls(request.url())?),
+                _ => unreachable!("scheme already validated above"),
+            },
+        };
+
+        let (mut tracked, handoff) = TrackedStream::new(stream, key, self.pool.clone());
+        encode_request(request, BufWriter::new(&mut tracked))?;
+        if self.auto_decompress && !caller_set_accept_encoding {
+            // Take the injected header off again, so a redirect hop or a Digest retry of this
+            // request does not mistake it for one the caller set.
+            request.headers_mut().remove(&HeaderName::ACCEPT_ENCODING);
+        }
+        let mut response = decode_response(BufReader::new(tracked))?;
+        if self.keep_alive && is_keep_alive_response(&response) {
+            handoff.mark_poolable();
+            // A known Content-Length body is read through a length-limiting reader that stops
+            // calling through to the socket once its budget is spent, so TrackedStream never
+            // sees the trailing zero-byte read it otherwise waits for; tell it the expected
+            // length instead so it can recognize a fully-delivered body on its own.
+            if let Some(length) = response
+                .header(&HeaderName::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+            {
+                handoff.set_expected_content_length(length);
             }
-            _ => Err(invalid_input_error(format!(
-                "Not supported URL scheme: {}",
-                request.url().scheme()
-            ))),
         }
+        if scheme == "https" {
+            if let Some(sts) = response.header(&HeaderName::STRICT_TRANSPORT_SECURITY) {
+                if let Ok(value) = sts.to_str() {
+                    self.hsts.record(&host, value);
+                }
+            }
+        }
+        if self.auto_decompress && !caller_set_accept_encoding {
+            response = decode_content_encoding(response);
+        }
+        Ok(response)
     }
 
-    fn connect_tcp(&self, addresses: SocketAddrIterator) -> Result<TcpStream> {
-        let mut stream = addresses.fold(Err(Error::new(
-            ErrorKind::InvalidInput,
-            "Not able to resolve the provide addresses",
-        )),
-        |e, addr| match e {
-            Ok(stream) => Ok(stream),
-            Err(_) => if let Some(timeout) = self.timeout {
-                TcpStream::connect_timeout(addr.clone(), timeout)
-            } else {
-                TcpStream::connect(addr.clone())
-            },
-        })?;
-
-        // stream.set_read_timeout(self.timeout)?;
-        // stream.set_write_timeout(self.timeout)?;
-        Ok(stream)
+    fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream> {
+        self.with_connect_retries(|| {
+            let addresses = resolve_socket_addresses(host, port)?;
+            addresses.fold(
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Not able to resolve the provide addresses",
+                )),
+                |e, addr| match e {
+                    Ok(stream) => Ok(stream),
+                    Err(_) => {
+                        if let Some(timeout) = self.timeout {
+                            TcpStream::connect_timeout(addr.clone(), timeout)
+                        } else {
+                            TcpStream::connect(addr.clone())
+                        }
+                    }
+                },
+            )
+        })
     }
 
     fn connect_tls(&self, url: &Url) -> Result<TlsStream> {
@@ -254,22 +580,42 @@ impl Client {
             None => 443
         };
 
+        self.with_connect_retries(|| {
+            if let Some(timeout) = self.timeout {
+                TlsStream::connect_timeout(host.to_string().as_str(), timeout, port, vec![])
+            } else {
+                TlsStream::connect(host.to_string().as_str(), port)
+            }
+        })
+    }
 
-
-        let stream = if let Some(timeout) = self.timeout {
-
-            TlsStream::connect_timeout(host.to_string().as_str(), timeout, port, vec![])
-        } else {
-            TlsStream::connect(host.to_string().as_str(), port)
-
-        }?;
-
-        // stream.set_read_timeout(self.timeout)?;
-        // stream.set_write_timeout(self.timeout)?;
-        Ok(stream)
+    /// Runs `connect` and, on failure, retries up to `self.connect_retries` more times with an
+    /// exponential backoff (`connect_retry_base_delay * 2^attempt`, capped and jittered),
+    /// sleeping via lunatic's cooperative sleep between rounds. Returns the last error once
+    /// retries are exhausted.
+    fn with_connect_retries<T>(&self, mut connect: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match connect() {
+                Ok(value) => return Ok(value),
+                Err(_) if attempt < self.connect_retries => {
+                    lunatic::sleep(connect_backoff(self.connect_retry_base_delay, attempt));
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 }
 
+/// `base * 2^attempt`, capped at 30 seconds, with a few milliseconds of jitter to avoid
+/// thundering-herd reconnects.
+fn connect_backoff(base: Duration, attempt: u32) -> Duration {
+    let scale = 1u32 << attempt.min(10);
+    let capped = base.saturating_mul(scale).min(Duration::from_secs(30));
+    capped + Duration::from_millis(rand::random::<u64>() % 50)
+}
+
 // Bad ports https://fetch.spec.whatwg.org/#bad-port
 // Should be sorted
 // const BAD_PORTS: [u16; 80] = [
@@ -280,34 +626,17 @@ impl Client {
 //     6697, 10080,
 // ];
 
-fn get_and_validate_socket_addresses(url: &Url, default_port: u16) -> Result<SocketAddrIterator> {
-    // let addresses = url.socket_addrs(|| Some(default_port))?;
-
-    let port = if let Some(port) = url.port() { port } else {default_port};
-    let host = match url.host_str() {
-        Some(x)=> x,
-        None=> {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "host not set"
-                ),
-            ))
-        }
-    };
-    let addresses = match lunatic::net::resolve(&format!("{}:{}", host, port)) {
-        Ok(x)=> x,
-        Err(e)=> {
-            println!("{}", e);
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "cant DNS resole the url: {}",
-                    url.host().unwrap().to_string()
-                ),
-            ))
-        }
-    };
+/// Default cap on the number of idle, keep-alive connections kept open per origin.
+const DEFAULT_MAX_IDLE_CONNECTIONS_PER_HOST: usize = 4;
+
+fn resolve_socket_addresses(host: &str, port: u16) -> Result<SocketAddrIterator> {
+    lunatic::net::resolve(&format!("{}:{}", host, port)).map_err(|e| {
+        println!("{}", e);
+        Error::new(
+            ErrorKind::Other,
+            format!("cant DNS resole the url: {}", host),
+        )
+    })
     // for address in addresses {
     //     if BAD_PORTS.binary_search(&address.port()).is_ok() {
     //         return Err(invalid_input_error(format!(
@@ -316,7 +645,62 @@ fn get_and_validate_socket_addresses(url: &Url, default_port: u16) -> Result<Soc
     //         )));
     //     }
     // }
-    Ok(addresses)
+}
+
+/// The total size of a downloaded file, combining a resumed request's `Content-Range` (or its
+/// `Content-Length` plus the bytes already on disk) with a fresh request's plain
+/// `Content-Length`. `None` if the server reported neither.
+fn total_download_size(response: &Response, downloaded_before_body: u64) -> Option<u64> {
+    if let Some(total) = response
+        .header(&HeaderName::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|range| range.rsplit('/').next())
+        .and_then(|total| total.parse::<u64>().ok())
+    {
+        return Some(total);
+    }
+    response
+        .header(&HeaderName::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|len| len.parse::<u64>().ok())
+        .map(|len| len + downloaded_before_body)
+}
+
+/// Whether a response may be followed by another one on the same connection: HTTP/1.1
+/// semantics, no explicit `Connection: close`, and a body whose end can be located without
+/// closing the connection (a known `Content-Length` or a chunked transfer encoding).
+fn is_keep_alive_response(response: &Response) -> bool {
+    let closes = response
+        .header(&HeaderName::CONNECTION)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.eq_ignore_ascii_case("close"));
+    if closes {
+        return false;
+    }
+    let has_known_length = response.header(&HeaderName::CONTENT_LENGTH).is_some()
+        || response
+            .header(&HeaderName::TRANSFER_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.eq_ignore_ascii_case("chunked"));
+    has_known_length || matches!(response.status(), Status::NO_CONTENT | Status::NOT_MODIFIED)
+}
+
+/// Decodes the response body in place when `Content-Encoding` names a supported encoding,
+/// and drops the `Content-Encoding`/`Content-Length` headers so the caller sees plaintext.
+fn decode_content_encoding(response: Response) -> Response {
+    let content_encoding = response
+        .header(&HeaderName::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let content_encoding = match content_encoding {
+        Some(encoding) if is_supported_encoding(&encoding) => encoding,
+        _ => return response,
+    };
+    response.map_body(|body| {
+        Box::new(DecodedBody::wrap(Some(content_encoding.as_str()), body)) as Box<dyn Read>
+    })
+    .without_header(&HeaderName::CONTENT_ENCODING)
+    .without_header(&HeaderName::CONTENT_LENGTH)
 }
 
 fn set_header_fallback(
@@ -466,4 +850,125 @@ mod tests {
         ).unwrap();
         assert_eq!(response.status(), Status::OK);
     }
+
+    #[lunatic_test::test]
+    fn keep_alive_reuses_a_pooled_connection_for_a_content_length_body() {
+        let mut client = Client::new();
+        client.set_keep_alive(true);
+        let url: Url = "http://example.com".parse().unwrap();
+        let key = ("http".to_string(), url.host_str().unwrap().to_string(), 80);
+
+        let response = client
+            .request(Request::builder(Method::GET, url.clone()).build())
+            .unwrap();
+        // Draining the body all the way is what triggers the pool handoff.
+        response.into_body().to_string().unwrap();
+        assert_eq!(
+            client.pool.idle_count(&key),
+            1,
+            "the first connection should have been returned to the pool once drained"
+        );
+
+        let response = client
+            .request(Request::builder(Method::GET, url).build())
+            .unwrap();
+        assert_eq!(
+            client.pool.idle_count(&key),
+            0,
+            "the second request should have taken the pooled connection instead of opening a new one"
+        );
+        response.into_body().to_string().unwrap();
+    }
+
+    fn response_with_headers(status: Status, headers: &[(HeaderName, &str)]) -> Response {
+        let mut builder = Response::builder(status);
+        for (name, value) in headers {
+            builder
+                .headers_mut()
+                .set(name.clone(), HeaderValue::new_unchecked(*value));
+        }
+        builder.with_body(std::io::Cursor::new(Vec::new())).build()
+    }
+
+    #[test]
+    fn keep_alive_requires_a_known_body_length() {
+        let response = response_with_headers(Status::OK, &[]);
+        assert!(!is_keep_alive_response(&response));
+    }
+
+    #[test]
+    fn keep_alive_with_content_length_is_reusable() {
+        let response =
+            response_with_headers(Status::OK, &[(HeaderName::CONTENT_LENGTH, "5")]);
+        assert!(is_keep_alive_response(&response));
+    }
+
+    #[test]
+    fn keep_alive_with_chunked_transfer_encoding_is_reusable() {
+        let response = response_with_headers(
+            Status::OK,
+            &[(HeaderName::TRANSFER_ENCODING, "chunked")],
+        );
+        assert!(is_keep_alive_response(&response));
+    }
+
+    #[test]
+    fn connection_close_is_never_reusable_even_with_a_known_length() {
+        let response = response_with_headers(
+            Status::OK,
+            &[
+                (HeaderName::CONTENT_LENGTH, "5"),
+                (HeaderName::CONNECTION, "close"),
+            ],
+        );
+        assert!(!is_keep_alive_response(&response));
+    }
+
+    #[test]
+    fn no_content_and_not_modified_are_reusable_without_a_length() {
+        assert!(is_keep_alive_response(&response_with_headers(
+            Status::NO_CONTENT,
+            &[]
+        )));
+        assert!(is_keep_alive_response(&response_with_headers(
+            Status::NOT_MODIFIED,
+            &[]
+        )));
+    }
+
+    #[test]
+    fn connect_backoff_doubles_and_caps_at_thirty_seconds() {
+        let base = Duration::from_millis(100);
+        // Jitter adds up to 50ms, so compare against the unjittered floor.
+        assert!(connect_backoff(base, 0) >= base);
+        assert!(connect_backoff(base, 0) < base + Duration::from_millis(50));
+        assert!(connect_backoff(base, 1) >= base * 2);
+        assert!(connect_backoff(base, 10) >= Duration::from_secs(30));
+        assert!(connect_backoff(base, 10) < Duration::from_secs(30) + Duration::from_millis(50));
+    }
+
+    #[test]
+    fn total_download_size_prefers_content_range_total() {
+        let response = response_with_headers(
+            Status::PARTIAL_CONTENT,
+            &[
+                (HeaderName::CONTENT_RANGE, "bytes 100-199/1000"),
+                (HeaderName::CONTENT_LENGTH, "100"),
+            ],
+        );
+        assert_eq!(total_download_size(&response, 100), Some(1000));
+    }
+
+    #[test]
+    fn total_download_size_adds_resumed_bytes_to_a_plain_content_length() {
+        let response = response_with_headers(Status::OK, &[(HeaderName::CONTENT_LENGTH, "50")]);
+        assert_eq!(total_download_size(&response, 0), Some(50));
+        assert_eq!(total_download_size(&response, 25), Some(75));
+    }
+
+    #[test]
+    fn total_download_size_is_none_without_a_length() {
+        let response = response_with_headers(Status::OK, &[]);
+        assert_eq!(total_download_size(&response, 0), None);
+    }
 }
@@ -0,0 +1,101 @@
+//! Transparent decoding of compressed response bodies.
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use std::io::{Read, Result};
+
+/// Wraps a response body, transparently decoding it according to the `Content-Encoding` that
+/// was announced for it. Bodies with an encoding this client doesn't understand are passed
+/// through unchanged.
+pub(crate) enum DecodedBody<R: Read> {
+    Identity(R),
+    Gzip(GzDecoder<R>),
+    Deflate(DeflateDecoder<R>),
+}
+
+impl<R: Read> DecodedBody<R> {
+    /// Picks the right decoder for `content_encoding`, falling back to passing `body` through
+    /// untouched when the encoding is absent or unsupported.
+    pub(crate) fn wrap(content_encoding: Option<&str>, body: R) -> Self {
+        match content_encoding {
+            Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => {
+                Self::Gzip(GzDecoder::new(body))
+            }
+            Some(encoding) if encoding.eq_ignore_ascii_case("deflate") => {
+                Self::Deflate(DeflateDecoder::new(body))
+            }
+            _ => Self::Identity(body),
+        }
+    }
+}
+
+/// Whether `content_encoding` names an encoding [`DecodedBody`] knows how to decode.
+pub(crate) fn is_supported_encoding(content_encoding: &str) -> bool {
+    content_encoding.eq_ignore_ascii_case("gzip") || content_encoding.eq_ignore_ascii_case("deflate")
+}
+
+impl<R: Read> Read for DecodedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Identity(r) => r.read(buf),
+            Self::Gzip(r) => r.read(buf),
+            Self::Deflate(r) => r.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn is_supported_encoding_is_case_insensitive() {
+        assert!(is_supported_encoding("gzip"));
+        assert!(is_supported_encoding("GZIP"));
+        assert!(is_supported_encoding("Deflate"));
+        assert!(!is_supported_encoding("br"));
+        assert!(!is_supported_encoding(""));
+    }
+
+    #[test]
+    fn identity_passes_body_through_unchanged() {
+        let mut body = DecodedBody::wrap(None, Cursor::new(b"hello".to_vec()));
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+    }
+
+    #[test]
+    fn unsupported_encoding_falls_back_to_identity() {
+        let mut body = DecodedBody::wrap(Some("br"), Cursor::new(b"hello".to_vec()));
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+    }
+
+    #[test]
+    fn gzip_is_decoded() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello gzip").unwrap();
+        let encoded = encoder.finish().unwrap();
+
+        let mut body = DecodedBody::wrap(Some("gzip"), Cursor::new(encoded));
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello gzip");
+    }
+
+    #[test]
+    fn deflate_is_decoded() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello deflate").unwrap();
+        let encoded = encoder.finish().unwrap();
+
+        let mut body = DecodedBody::wrap(Some("deflate"), Cursor::new(encoded));
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello deflate");
+    }
+}
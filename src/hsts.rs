@@ -0,0 +1,150 @@
+//! HTTP Strict Transport Security (HSTS) upgrade store.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Treated as "does not expire in practice"; used for preloaded entries, which have no
+/// `max-age` of their own.
+const PRELOAD_LIFETIME: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+struct HstsEntry {
+    expires_at: Instant,
+    include_subdomains: bool,
+}
+
+/// Tracks hosts that have asked to be reached over HTTPS only, via the
+/// `Strict-Transport-Security` response header.
+#[derive(Default)]
+pub(crate) struct HstsStore {
+    entries: Mutex<HashMap<String, HstsEntry>>,
+}
+
+impl HstsStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a `Strict-Transport-Security` header value received for `host` and updates the
+    /// store accordingly. A `max-age=0` purges any existing entry for the host.
+    pub(crate) fn record(&self, host: &str, value: &str) {
+        let mut max_age = None;
+        let mut include_subdomains = false;
+        for directive in value.split(';') {
+            let directive = directive.trim();
+            if directive.eq_ignore_ascii_case("includeSubDomains") {
+                include_subdomains = true;
+            } else if let Some(seconds) = directive
+                .split_once('=')
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("max-age"))
+                .map(|(_, value)| value.trim())
+            {
+                max_age = seconds.parse::<u64>().ok();
+            }
+        }
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None => return,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if max_age == 0 {
+            entries.remove(host);
+        } else {
+            entries.insert(
+                host.to_string(),
+                HstsEntry {
+                    expires_at: Instant::now() + Duration::from_secs(max_age),
+                    include_subdomains,
+                },
+            );
+        }
+    }
+
+    /// Seeds a preloaded entry that does not rely on ever having seen the header, e.g. from a
+    /// vendored HSTS preload list.
+    pub(crate) fn preload(&self, host: impl Into<String>, include_subdomains: bool) {
+        self.entries.lock().unwrap().insert(
+            host.into(),
+            HstsEntry {
+                expires_at: Instant::now() + PRELOAD_LIFETIME,
+                include_subdomains,
+            },
+        );
+    }
+
+    /// Whether `host` should be upgraded to HTTPS: either it has a non-expired entry of its
+    /// own, or a parent domain does with `includeSubDomains` set.
+    pub(crate) fn should_upgrade(&self, host: &str) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        if entries
+            .get(host)
+            .map_or(false, |entry| entry.expires_at > now)
+        {
+            return true;
+        }
+        let mut parent = host;
+        while let Some((_, rest)) = parent.split_once('.') {
+            if let Some(entry) = entries.get(rest) {
+                if entry.expires_at > now && entry.include_subdomains {
+                    return true;
+                }
+            }
+            parent = rest;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_host_is_not_upgraded() {
+        let store = HstsStore::new();
+        assert!(!store.should_upgrade("example.com"));
+    }
+
+    #[test]
+    fn recorded_host_is_upgraded() {
+        let store = HstsStore::new();
+        store.record("example.com", "max-age=3600");
+        assert!(store.should_upgrade("example.com"));
+        assert!(!store.should_upgrade("sub.example.com"));
+    }
+
+    #[test]
+    fn include_subdomains_upgrades_host_and_children_but_not_unrelated_hosts() {
+        let store = HstsStore::new();
+        store.record("example.com", "max-age=3600; includeSubDomains");
+        assert!(store.should_upgrade("example.com"));
+        assert!(store.should_upgrade("sub.example.com"));
+        assert!(!store.should_upgrade("other.com"));
+    }
+
+    #[test]
+    fn max_age_zero_purges_the_entry() {
+        let store = HstsStore::new();
+        store.record("example.com", "max-age=3600");
+        assert!(store.should_upgrade("example.com"));
+        store.record("example.com", "max-age=0");
+        assert!(!store.should_upgrade("example.com"));
+    }
+
+    #[test]
+    fn missing_max_age_is_ignored() {
+        let store = HstsStore::new();
+        store.record("example.com", "includeSubDomains");
+        assert!(!store.should_upgrade("example.com"));
+    }
+
+    #[test]
+    fn preload_upgrades_without_ever_seeing_the_header() {
+        let store = HstsStore::new();
+        store.preload("example.com", true);
+        assert!(store.should_upgrade("example.com"));
+        assert!(store.should_upgrade("sub.example.com"));
+    }
+}
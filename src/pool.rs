@@ -0,0 +1,293 @@
+//! Idle connection pooling for keep-alive reuse.
+
+use lunatic::net::{TcpStream, TlsStream};
+use std::collections::HashMap;
+use std::io::{Read, Result, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The origin a pooled connection belongs to: `(scheme, host, port)`.
+pub(crate) type ConnectionKey = (String, String, u16);
+
+/// Read timeout used to probe a pooled connection for liveness before handing it back to a
+/// caller. Short enough to be a negligible delay, but non-zero since a zero duration is not a
+/// portable way to request a non-blocking read.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Either side of a connection that can be kept alive and handed back to the pool.
+pub(crate) enum PooledStream {
+    Tcp(TcpStream),
+    Tls(TlsStream),
+}
+
+impl PooledStream {
+    /// Best-effort check that the peer has not half-closed the connection while it was idle.
+    /// A stream that looks dead is dropped instead of being handed back to a caller. The
+    /// stream's read timeout is restored to whatever it was before the probe, so a connection
+    /// that survives is handed back ready for a normal blocking (or previously configured) read.
+    ///
+    /// The probe uses [`PROBE_TIMEOUT`] rather than a zero duration: a `0` read/write timeout is
+    /// rejected outright (`ErrorKind::InvalidInput`) by `std::net::TcpStream::set_read_timeout`
+    /// on most platforms, and nothing here guarantees `lunatic::net`'s streams special-case it as
+    /// "non-blocking poll" instead of also erroring or, worse, silently keeping the previous
+    /// (possibly blocking) timeout and hanging the caller.
+    fn is_dead(&mut self) -> bool {
+        let probe = match self {
+            PooledStream::Tcp(stream) => {
+                let previous_timeout = stream.read_timeout().unwrap_or(None);
+                let _ = stream.set_read_timeout(Some(PROBE_TIMEOUT));
+                let probe = stream.read(&mut [0; 1]);
+                let _ = stream.set_read_timeout(previous_timeout);
+                probe
+            }
+            PooledStream::Tls(stream) => {
+                let previous_timeout = stream.read_timeout().unwrap_or(None);
+                let _ = stream.set_read_timeout(Some(PROBE_TIMEOUT));
+                let probe = stream.read(&mut [0; 1]);
+                let _ = stream.set_read_timeout(previous_timeout);
+                probe
+            }
+        };
+        match probe {
+            Ok(0) => true,
+            Ok(_) => true,
+            Err(e) => !matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut),
+        }
+    }
+}
+
+impl Read for PooledStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            PooledStream::Tcp(stream) => stream.read(buf),
+            PooledStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for PooledStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self {
+            PooledStream::Tcp(stream) => stream.write(buf),
+            PooledStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            PooledStream::Tcp(stream) => stream.flush(),
+            PooledStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A pool of idle connections, keyed by `(scheme, host, port)`.
+pub(crate) struct ConnectionPool {
+    idle: Mutex<HashMap<ConnectionKey, Vec<PooledStream>>>,
+    max_idle_per_host: usize,
+}
+
+impl ConnectionPool {
+    pub(crate) fn new(max_idle_per_host: usize) -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            max_idle_per_host,
+        }
+    }
+
+    /// Pops a still-alive idle connection for `key`, discarding any dead ones found along the way.
+    pub(crate) fn take(&self, key: &ConnectionKey) -> Option<PooledStream> {
+        let mut idle = self.idle.lock().unwrap();
+        let bucket = idle.get_mut(key)?;
+        while let Some(mut stream) = bucket.pop() {
+            if !stream.is_dead() {
+                return Some(stream);
+            }
+        }
+        None
+    }
+
+    /// Returns a connection to the pool, subject to the per-host idle cap.
+    pub(crate) fn put(&self, key: ConnectionKey, stream: PooledStream) {
+        if self.max_idle_per_host == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let bucket = idle.entry(key).or_default();
+        if bucket.len() < self.max_idle_per_host {
+            bucket.push(stream);
+        }
+    }
+
+    /// Number of idle connections currently pooled for `key`.
+    #[cfg(test)]
+    pub(crate) fn idle_count(&self, key: &ConnectionKey) -> usize {
+        self.idle.lock().unwrap().get(key).map_or(0, Vec::len)
+    }
+}
+
+/// Tracks eligibility for reuse while the response body is streamed, and hands the underlying
+/// stream back to the pool once it has been fully drained.
+///
+/// The stream is only returned when [`PoolHandoff::mark_poolable`] was called (the response
+/// turned out to be keep-alive eligible) *and* the body was fully drained. That's either
+/// observed directly as an `Ok(0)` read (the case for a chunked body, whose decoder keeps
+/// reading from here through the terminating chunk), or, when the response's `Content-Length`
+/// was also recorded via [`PoolHandoff::set_expected_content_length`], inferred once that many bytes have passed
+/// through this wrapper — a known-length body is read by a `Read::take`-style limiter that
+/// stops calling through to here as soon as its budget is exhausted, so it never produces the
+/// trailing zero-byte read this wrapper would otherwise wait for.
+pub(crate) struct TrackedStream {
+    stream: Option<PooledStream>,
+    key: ConnectionKey,
+    pool: Arc<ConnectionPool>,
+    handoff: Arc<PoolHandoff>,
+    drained: bool,
+    bytes_read: u64,
+}
+
+/// Sentinel meaning "no known `Content-Length`", i.e. a chunked (or bodiless) response.
+const NO_EXPECTED_CONTENT_LENGTH: u64 = u64::MAX;
+
+pub(crate) struct PoolHandoff {
+    poolable: AtomicBool,
+    expected_content_length: AtomicU64,
+}
+
+impl Default for PoolHandoff {
+    fn default() -> Self {
+        Self {
+            poolable: AtomicBool::new(false),
+            expected_content_length: AtomicU64::new(NO_EXPECTED_CONTENT_LENGTH),
+        }
+    }
+}
+
+impl PoolHandoff {
+    pub(crate) fn mark_poolable(&self) {
+        self.poolable.store(true, Ordering::Relaxed);
+    }
+
+    /// Records the response's `Content-Length`, so a [`TrackedStream`] can recognize its body as
+    /// fully delivered once that many bytes have passed through it (see the struct docs).
+    pub(crate) fn set_expected_content_length(&self, length: u64) {
+        self.expected_content_length.store(length, Ordering::Relaxed);
+    }
+
+    fn is_content_length_satisfied(&self, bytes_read: u64) -> bool {
+        match self.expected_content_length.load(Ordering::Relaxed) {
+            NO_EXPECTED_CONTENT_LENGTH => false,
+            expected => bytes_read >= expected,
+        }
+    }
+}
+
+impl TrackedStream {
+    pub(crate) fn new(
+        stream: PooledStream,
+        key: ConnectionKey,
+        pool: Arc<ConnectionPool>,
+    ) -> (Self, Arc<PoolHandoff>) {
+        let handoff = Arc::new(PoolHandoff::default());
+        (
+            Self {
+                stream: Some(stream),
+                key,
+                pool,
+                handoff: handoff.clone(),
+                drained: false,
+                bytes_read: 0,
+            },
+            handoff,
+        )
+    }
+}
+
+impl Read for TrackedStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self
+            .stream
+            .as_mut()
+            .expect("TrackedStream used after being returned to the pool")
+            .read(buf)?;
+        if n == 0 {
+            self.drained = true;
+        } else {
+            self.bytes_read += n as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for TrackedStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.stream
+            .as_mut()
+            .expect("TrackedStream used after being returned to the pool")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stream
+            .as_mut()
+            .expect("TrackedStream used after being returned to the pool")
+            .flush()
+    }
+}
+
+impl Drop for TrackedStream {
+    fn drop(&mut self) {
+        let fully_delivered =
+            self.drained || self.handoff.is_content_length_satisfied(self.bytes_read);
+        if fully_delivered && self.handoff.poolable.load(Ordering::Relaxed) {
+            if let Some(stream) = self.stream.take() {
+                self.pool.put(self.key.clone(), stream);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect() -> PooledStream {
+        PooledStream::Tcp(TcpStream::connect("example.com:80").unwrap())
+    }
+
+    #[lunatic_test::test]
+    fn pool_evicts_past_max_idle_per_host() {
+        let pool = ConnectionPool::new(1);
+        let key: ConnectionKey = ("http".to_string(), "example.com".to_string(), 80);
+        pool.put(key.clone(), connect());
+        pool.put(key.clone(), connect());
+        assert!(pool.take(&key).is_some());
+        assert!(pool.take(&key).is_none());
+    }
+
+    #[lunatic_test::test]
+    fn pool_with_zero_capacity_never_stores() {
+        let pool = ConnectionPool::new(0);
+        let key: ConnectionKey = ("http".to_string(), "example.com".to_string(), 80);
+        pool.put(key.clone(), connect());
+        assert!(pool.take(&key).is_none());
+    }
+
+    #[lunatic_test::test]
+    fn is_dead_restores_the_read_timeout() {
+        let mut stream = connect();
+        let PooledStream::Tcp(tcp) = &stream else {
+            unreachable!()
+        };
+        tcp.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        assert!(!stream.is_dead());
+        let PooledStream::Tcp(tcp) = &stream else {
+            unreachable!()
+        };
+        assert_eq!(
+            tcp.read_timeout().unwrap(),
+            Some(Duration::from_secs(30))
+        );
+    }
+}